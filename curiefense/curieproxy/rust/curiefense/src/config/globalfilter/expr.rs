@@ -0,0 +1,568 @@
+/// A small, self-contained expression language for `GlobalFilterEntryE::Expr` conditions, e.g.
+/// `geo.country == "us" and args.count() > 5 and header("user-agent") matches "^curl"`.
+///
+/// Compilation (tokenize + parse, including compiling any `matches` pattern to a `regex::Regex`)
+/// happens once, at config-resolution time, in `compile`. Evaluation walks the resulting AST
+/// against a request and returns both the boolean result and the set of `Location`s that
+/// contributed, so callers can still localize tags the way `MatchResult` does for the flat
+/// And/Or entries.
+use crate::interface::Location;
+use crate::utils::RequestInfo;
+use regex::Regex;
+use std::collections::HashSet;
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub struct CompiledExpr {
+    ast: Expr,
+    source: String,
+}
+
+impl PartialEq for CompiledExpr {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+impl Eq for CompiledExpr {}
+
+impl fmt::Display for CompiledExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Cmp(Box<Expr>, CmpOp, Box<Expr>),
+    Call(String, Vec<Expr>),
+    Var(String),
+    Str(String),
+    Num(f64),
+    /// A `matches` pattern literal, pre-compiled once in `compile` so evaluation never calls
+    /// `Regex::new` on the hot path. Only ever produced by `precompile_regexes` from a `Str`
+    /// right-hand-side of `CmpOp::Matches`.
+    Regex(Regex),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Matches,
+    In,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Str(String),
+    Num(f64),
+    Bool(bool),
+}
+
+impl Value {
+    fn truthy(&self) -> bool {
+        match self {
+            Value::Bool(b) => *b,
+            Value::Str(s) => !s.is_empty(),
+            Value::Num(n) => *n != 0.0,
+        }
+    }
+
+    fn as_str(&self) -> String {
+        match self {
+            Value::Str(s) => s.clone(),
+            Value::Num(n) => n.to_string(),
+            Value::Bool(b) => b.to_string(),
+        }
+    }
+}
+
+// ---- tokenizer ----
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Ident(String),
+    Str(String),
+    Num(f64),
+    LParen,
+    RParen,
+    Comma,
+    Dot,
+    Op(String),
+    Eof,
+}
+
+fn tokenize(src: &str) -> Result<Vec<Tok>, String> {
+    let mut toks = Vec::new();
+    let chars: Vec<char> = src.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        match c {
+            '(' => {
+                toks.push(Tok::LParen);
+                i += 1;
+            }
+            ')' => {
+                toks.push(Tok::RParen);
+                i += 1;
+            }
+            ',' => {
+                toks.push(Tok::Comma);
+                i += 1;
+            }
+            '.' if !chars.get(i + 1).map(|c| c.is_ascii_digit()).unwrap_or(false) => {
+                toks.push(Tok::Dot);
+                i += 1;
+            }
+            '"' => {
+                let mut s = String::new();
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    s.push(chars[i]);
+                    i += 1;
+                }
+                if i >= chars.len() {
+                    return Err("unterminated string literal".to_string());
+                }
+                i += 1;
+                toks.push(Tok::Str(s));
+            }
+            '=' | '!' | '<' | '>' => {
+                let mut op = c.to_string();
+                if chars.get(i + 1) == Some(&'=') {
+                    op.push('=');
+                    i += 2;
+                } else {
+                    i += 1;
+                }
+                toks.push(Tok::Op(op));
+            }
+            _ if c.is_ascii_digit() => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let numstr: String = chars[start..i].iter().collect();
+                let n = numstr.parse::<f64>().map_err(|_| format!("bad number literal {}", numstr))?;
+                toks.push(Tok::Num(n));
+            }
+            _ if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                toks.push(Tok::Ident(chars[start..i].iter().collect()));
+            }
+            _ => return Err(format!("unexpected character '{}'", c)),
+        }
+    }
+    toks.push(Tok::Eof);
+    Ok(toks)
+}
+
+// ---- precedence-climbing parser ----
+
+struct Parser {
+    toks: Vec<Tok>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Tok {
+        &self.toks[self.pos]
+    }
+
+    fn next(&mut self) -> Tok {
+        let t = self.toks[self.pos].clone();
+        if self.pos + 1 < self.toks.len() {
+            self.pos += 1;
+        }
+        t
+    }
+
+    fn eat_ident(&mut self, name: &str) -> bool {
+        if matches!(self.peek(), Tok::Ident(s) if s == name) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    // or_expr := and_expr ("or" and_expr)*
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.parse_and()?;
+        while self.eat_ident("or") {
+            let rhs = self.parse_and()?;
+            lhs = Expr::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    // and_expr := unary ("and" unary)*
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.parse_unary()?;
+        while self.eat_ident("and") {
+            let rhs = self.parse_unary()?;
+            lhs = Expr::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        if self.eat_ident("not") {
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+        self.parse_cmp()
+    }
+
+    // cmp := primary (("=="|"!="|"<"|">"|"matches"|"in") primary)?
+    fn parse_cmp(&mut self) -> Result<Expr, String> {
+        let lhs = self.parse_primary()?;
+        let op = match self.peek().clone() {
+            Tok::Op(ref s) if s == "==" => Some(CmpOp::Eq),
+            Tok::Op(ref s) if s == "!=" => Some(CmpOp::Ne),
+            Tok::Op(ref s) if s == "<" => Some(CmpOp::Lt),
+            Tok::Op(ref s) if s == ">" => Some(CmpOp::Gt),
+            Tok::Ident(ref s) if s == "matches" => Some(CmpOp::Matches),
+            Tok::Ident(ref s) if s == "in" => Some(CmpOp::In),
+            _ => None,
+        };
+        match op {
+            None => Ok(lhs),
+            Some(op) => {
+                self.next();
+                let rhs = self.parse_primary()?;
+                Ok(Expr::Cmp(Box::new(lhs), op, Box::new(rhs)))
+            }
+        }
+    }
+
+    // primary := "(" or_expr ")" | string | number | ident ("." ident)* ("(" args ")")?
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Tok::LParen => {
+                let inner = self.parse_or()?;
+                if !matches!(self.next(), Tok::RParen) {
+                    return Err("expected closing ')'".to_string());
+                }
+                Ok(inner)
+            }
+            Tok::Str(s) => Ok(Expr::Str(s)),
+            Tok::Num(n) => Ok(Expr::Num(n)),
+            Tok::Ident(name) => {
+                let mut path = name;
+                while matches!(self.peek(), Tok::Dot) {
+                    self.next();
+                    match self.next() {
+                        Tok::Ident(sub) => {
+                            path.push('.');
+                            path.push_str(&sub);
+                        }
+                        _ => return Err("expected identifier after '.'".to_string()),
+                    }
+                }
+                if matches!(self.peek(), Tok::LParen) {
+                    self.next();
+                    let mut args = Vec::new();
+                    if !matches!(self.peek(), Tok::RParen) {
+                        loop {
+                            args.push(self.parse_or()?);
+                            if matches!(self.peek(), Tok::Comma) {
+                                self.next();
+                            } else {
+                                break;
+                            }
+                        }
+                    }
+                    if !matches!(self.next(), Tok::RParen) {
+                        return Err("expected closing ')'".to_string());
+                    }
+                    Ok(Expr::Call(path, args))
+                } else {
+                    Ok(Expr::Var(path))
+                }
+            }
+            other => Err(format!("unexpected token {:?}", other)),
+        }
+    }
+}
+
+/// Tokenizes, parses, and pre-compiles (e.g. `matches` regexes) a condition string. Run once at
+/// config-resolution time so evaluation stays allocation-light on the hot path.
+pub fn compile(source: &str) -> anyhow::Result<CompiledExpr> {
+    let toks = tokenize(source).map_err(|e| anyhow::anyhow!("{}", e))?;
+    let mut parser = Parser { toks, pos: 0 };
+    let mut ast = parser.parse_or().map_err(|e| anyhow::anyhow!("{}", e))?;
+    if !matches!(parser.peek(), Tok::Eof) {
+        anyhow::bail!("trailing tokens after expression");
+    }
+    precompile_regexes(&mut ast)?;
+    Ok(CompiledExpr {
+        ast,
+        source: source.to_string(),
+    })
+}
+
+/// Walks the AST once and replaces every literal `matches` right-hand-side with its compiled
+/// `regex::Regex`, so a bad pattern is rejected at config-resolution time and evaluation never
+/// calls `Regex::new` on the request path. A dynamic (non-literal) right-hand-side, e.g.
+/// `matches header("x-pattern")`, is still compiled at evaluation time since its value isn't
+/// known until then.
+fn precompile_regexes(e: &mut Expr) -> anyhow::Result<()> {
+    match e {
+        Expr::And(a, b) | Expr::Or(a, b) => {
+            precompile_regexes(a)?;
+            precompile_regexes(b)
+        }
+        Expr::Not(a) => precompile_regexes(a),
+        Expr::Cmp(lhs, CmpOp::Matches, rhs) => {
+            precompile_regexes(lhs)?;
+            if let Expr::Str(pat) = rhs.as_ref() {
+                let re = Regex::new(pat)?;
+                **rhs = Expr::Regex(re);
+            }
+            Ok(())
+        }
+        Expr::Cmp(a, _, b) => {
+            precompile_regexes(a)?;
+            precompile_regexes(b)
+        }
+        Expr::Call(_, args) => args.iter_mut().try_for_each(precompile_regexes),
+        Expr::Var(_) | Expr::Str(_) | Expr::Num(_) | Expr::Regex(_) => Ok(()),
+    }
+}
+
+/// The result of evaluating a `CompiledExpr`: whether it matched, and which request `Location`s
+/// were read while evaluating it (so tags can still be localized as with the flat entries).
+pub struct ExprResult {
+    pub matching: bool,
+    pub matched: HashSet<Location>,
+}
+
+fn resolve_var(rinfo: &RequestInfo, path: &str) -> Value {
+    match path {
+        "ip" => Value::Str(rinfo.rinfo.geoip.ip.map(|ip| ip.to_string()).unwrap_or_default()),
+        "geo.country" => Value::Str(rinfo.rinfo.geoip.country_iso.clone().unwrap_or_default()),
+        "geo.asn" => Value::Num(rinfo.rinfo.geoip.asn.map(|a| a as f64).unwrap_or(-1.0)),
+        "host" => Value::Str(rinfo.rinfo.host.clone()),
+        "method" => Value::Str(rinfo.rinfo.meta.method.clone()),
+        "path" => Value::Str(rinfo.rinfo.qinfo.qpath.clone()),
+        _ => Value::Str(String::new()),
+    }
+}
+
+fn call_fn(rinfo: &RequestInfo, name: &str, args: &[Expr], locs: &mut HashSet<Location>) -> Value {
+    match name {
+        "lower" => Value::Str(eval_str(rinfo, args.first(), locs).to_lowercase()),
+        "contains" => {
+            let haystack = eval_str(rinfo, args.first(), locs);
+            let needle = eval_str(rinfo, args.get(1), locs);
+            Value::Bool(haystack.contains(&needle))
+        }
+        "args.count" => Value::Num(rinfo.rinfo.qinfo.args.len() as f64),
+        "header" => {
+            let key = eval_str(rinfo, args.first(), locs);
+            match rinfo.headers.get(&key) {
+                Some(v) => {
+                    locs.insert(Location::HeaderValue(key, v.to_string()));
+                    Value::Str(v.to_string())
+                }
+                None => Value::Str(String::new()),
+            }
+        }
+        _ => Value::Bool(false),
+    }
+}
+
+fn eval_str(rinfo: &RequestInfo, arg: Option<&Expr>, locs: &mut HashSet<Location>) -> String {
+    match arg {
+        Some(e) => eval_value(rinfo, e, locs).as_str(),
+        None => String::new(),
+    }
+}
+
+fn eval_value(rinfo: &RequestInfo, e: &Expr, locs: &mut HashSet<Location>) -> Value {
+    match e {
+        Expr::Str(s) => Value::Str(s.clone()),
+        Expr::Num(n) => Value::Num(*n),
+        Expr::Var(path) => {
+            if path == "args" {
+                locs.insert(Location::Request);
+            }
+            resolve_var(rinfo, path)
+        }
+        Expr::Call(name, args) => call_fn(rinfo, name, args, locs),
+        Expr::Regex(re) => Value::Str(re.as_str().to_string()),
+        Expr::And(_, _) | Expr::Or(_, _) | Expr::Not(_) | Expr::Cmp(_, _, _) => {
+            Value::Bool(eval_bool(rinfo, e, locs))
+        }
+    }
+}
+
+fn eval_bool(rinfo: &RequestInfo, e: &Expr, locs: &mut HashSet<Location>) -> bool {
+    match e {
+        Expr::And(a, b) => eval_bool(rinfo, a, locs) && eval_bool(rinfo, b, locs),
+        Expr::Or(a, b) => eval_bool(rinfo, a, locs) | eval_bool(rinfo, b, locs),
+        Expr::Not(a) => !eval_bool(rinfo, a, locs),
+        Expr::Cmp(lhs, op, rhs) => {
+            let l = eval_value(rinfo, lhs, locs);
+            match op {
+                CmpOp::Eq => l.as_str() == eval_value(rinfo, rhs, locs).as_str(),
+                CmpOp::Ne => l.as_str() != eval_value(rinfo, rhs, locs).as_str(),
+                CmpOp::Lt => matches!((l, eval_value(rinfo, rhs, locs)), (Value::Num(a), Value::Num(b)) if a < b),
+                CmpOp::Gt => matches!((l, eval_value(rinfo, rhs, locs)), (Value::Num(a), Value::Num(b)) if a > b),
+                CmpOp::In => eval_value(rinfo, rhs, locs).as_str().contains(&l.as_str()),
+                CmpOp::Matches => match rhs.as_ref() {
+                    // The common case: `precompile_regexes` already turned a literal pattern
+                    // into a compiled `Regex`, so matching a request never recompiles it.
+                    Expr::Regex(re) => re.is_match(&l.as_str()),
+                    other => Regex::new(&eval_value(rinfo, other, locs).as_str())
+                        .map(|re| re.is_match(&l.as_str()))
+                        .unwrap_or(false),
+                },
+            }
+        }
+        _ => eval_value(rinfo, e, locs).truthy(),
+    }
+}
+
+/// Evaluates a compiled expression against a request, returning the boolean result and the
+/// `Location`s that contributed to it.
+pub fn eval(compiled: &CompiledExpr, rinfo: &RequestInfo) -> ExprResult {
+    let mut matched = HashSet::new();
+    let matching = eval_bool(rinfo, &compiled.ast, &mut matched);
+    // A condition that never reads a header/arg/cookie (e.g. `geo.country == "us"`) contributes
+    // no `Location` on its own; anchor it to the request as a whole so tag localization still
+    // has something to point at, the same way `Method`/`Authority` entries do in `check_entry`.
+    if matching && matched.is_empty() {
+        matched.insert(Location::Request);
+    }
+    ExprResult { matching, matched }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logs::Logs;
+    use crate::utils::map_request;
+    use crate::utils::{RawRequest, RequestMeta};
+    use std::collections::HashMap;
+
+    fn mk_rinfo() -> RequestInfo {
+        let raw_headers = [
+            ("content-type", "application/json"),
+            (":method", "GET"),
+            (":authority", "localhost:30081"),
+            (":path", "/admin?lol=boo&bar=bze"),
+            ("user-agent", "curl/7.58.0"),
+        ];
+        let mut headers = HashMap::<String, String>::new();
+        let mut attrs = HashMap::<String, String>::new();
+        for (k, v) in raw_headers.iter() {
+            match k.strip_prefix(':') {
+                None => {
+                    headers.insert(k.to_string(), v.to_string());
+                }
+                Some(ak) => {
+                    attrs.insert(ak.to_string(), v.to_string());
+                }
+            }
+        }
+        let meta = RequestMeta::from_map(attrs).unwrap();
+        let mut logs = Logs::default();
+        map_request(
+            &mut logs,
+            &[],
+            &[],
+            500,
+            &RawRequest {
+                ipstr: "52.78.12.56".to_string(),
+                headers,
+                meta,
+                mbody: None,
+            },
+        )
+    }
+
+    fn eval_src(src: &str) -> ExprResult {
+        eval(&compile(src).unwrap(), &mk_rinfo())
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        // would be `false` if `or` bound tighter than `and`
+        assert!(eval_src("1 == 1 or 1 == 2 and 2 == 3").matching);
+        assert!(!eval_src("(1 == 2 or 1 == 1) and 2 == 3").matching);
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and() {
+        // would be `true` if `and` bound tighter than `not`
+        assert!(!eval_src("not 1 == 2 and 1 == 2").matching);
+    }
+
+    #[test]
+    fn parens_override_precedence() {
+        assert!(eval_src("not (1 == 1 and 1 == 2)").matching);
+    }
+
+    #[test]
+    fn matches_literal_pattern() {
+        assert!(eval_src("method matches \"^GE.$\"").matching);
+        assert!(!eval_src("method matches \"^PO.*$\"").matching);
+    }
+
+    #[test]
+    fn in_checks_substring() {
+        assert!(eval_src("\"admin\" in path").matching);
+        assert!(!eval_src("\"nope\" in path").matching);
+    }
+
+    #[test]
+    fn args_count() {
+        assert!(eval_src("args.count() == 2").matching);
+        assert!(!eval_src("args.count() == 0").matching);
+    }
+
+    #[test]
+    fn header_call_contributes_a_location() {
+        let r = eval_src("header(\"user-agent\") matches \"^curl\"");
+        assert!(r.matching);
+        assert!(r
+            .matched
+            .contains(&Location::HeaderValue("user-agent".to_string(), "curl/7.58.0".to_string())));
+    }
+
+    #[test]
+    fn location_less_condition_anchors_to_request() {
+        let r = eval_src("method == \"GET\"");
+        assert!(r.matching);
+        assert_eq!(r.matched, std::iter::once(Location::Request).collect());
+    }
+
+    #[test]
+    fn compile_rejects_bad_regex() {
+        assert!(compile("method matches \"(\"").is_err());
+    }
+
+    #[test]
+    fn compile_rejects_trailing_tokens() {
+        assert!(compile("1 == 1 1").is_err());
+    }
+
+    #[test]
+    fn compile_rejects_unterminated_string() {
+        assert!(compile("method == \"unterminated").is_err());
+    }
+}
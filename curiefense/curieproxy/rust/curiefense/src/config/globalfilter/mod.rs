@@ -0,0 +1,125 @@
+/// Global filter (a.k.a "ACL"-adjacent allow/deny) configuration: the resolved, request-matching
+/// representation of an operator-authored section tree. `tag_request` (in `crate::tagging`)
+/// walks these structures for every request.
+use crate::config::raw::Relation;
+use crate::interface::SimpleAction;
+use regex::Regex;
+use std::collections::HashSet;
+use std::net::IpAddr;
+
+pub mod expr;
+pub mod radix;
+
+pub use expr::CompiledExpr;
+pub use radix::NetworkMatcher;
+
+/// A single `key`/`value` match, such as a header or argument name plus its expected value.
+#[derive(Debug, Clone)]
+pub struct PairEntry {
+    pub key: String,
+    pub exact: String,
+    pub re: Option<Regex>,
+}
+
+/// A single scalar match, such as a path or a country code.
+#[derive(Debug, Clone)]
+pub struct SingleEntry {
+    pub exact: String,
+    pub re: Option<Regex>,
+}
+
+#[derive(Debug, Clone)]
+pub enum GlobalFilterEntryE {
+    Ip(IpAddr),
+    Network(ipnet::IpNet),
+    Range4(ipnet::Ipv4Net),
+    Range6(ipnet::Ipv6Net),
+    Path(SingleEntry),
+    Query(SingleEntry),
+    Uri(SingleEntry),
+    Country(SingleEntry),
+    Region(SingleEntry),
+    SubRegion(SingleEntry),
+    Method(SingleEntry),
+    Header(PairEntry),
+    Args(PairEntry),
+    Cookies(PairEntry),
+    Asn(u32),
+    Company(SingleEntry),
+    Authority(SingleEntry),
+    Tag(SingleEntry),
+    /// Matches one of the anonymizer sub-categories reported by the GeoIP anonymous-IP database:
+    /// `"tor"`, `"public_proxy"`, or `"anonymous"`. See `GeoIp::is_tor`/`is_public_proxy`/`is_anonymous`.
+    AnonymousType(SingleEntry),
+    /// Matches requests originating from a known hosting/datacenter IP range (`GeoIp::is_hosting`).
+    Hosting,
+    /// An arbitrary boolean condition written in the small expression language (see
+    /// `globalfilter::expr`), e.g. `geo.country == "us" and args.count() > 5`.
+    Expr(CompiledExpr),
+}
+
+#[derive(Debug, Clone)]
+pub struct GlobalFilterEntry {
+    pub negated: bool,
+    pub entry: GlobalFilterEntryE,
+}
+
+/// A flat And/Or group of entries.
+#[derive(Debug, Clone)]
+pub struct GlobalFilterSSection {
+    pub relation: Relation,
+    pub entries: Vec<GlobalFilterEntry>,
+    /// Precomputed at construction time by `new`: `Some` when `entries` is a pure `Or` of
+    /// non-negated IP/Network/Range4/Range6 entries, letting `check_subsection` do a single radix-trie
+    /// lookup instead of the linear scan.
+    pub matcher: Option<NetworkMatcher>,
+}
+
+impl GlobalFilterSSection {
+    pub fn new(relation: Relation, entries: Vec<GlobalFilterEntry>) -> Self {
+        let matcher = NetworkMatcher::try_build(relation, &entries);
+        GlobalFilterSSection {
+            relation,
+            entries,
+            matcher,
+        }
+    }
+}
+
+/// A top level rule: a tree of subsections, the tags it adds when matched, and the action to
+/// take (or `None`, meaning "tag only").
+#[derive(Debug, Clone)]
+pub struct GlobalFilterSection {
+    pub id: String,
+    pub name: String,
+    pub relation: Relation,
+    pub sections: Vec<GlobalFilterSSection>,
+    pub tags: crate::interface::Tags,
+    pub action: Option<SimpleAction>,
+}
+
+/// Pre-normalizes the IP-related entries of a subsection so that duplicate CIDRs (which show up
+/// often once blocklists are concatenated) aren't evaluated more than once per request. This is
+/// a light, allocation-only pass; see `NetworkMatcher` for the O(address-length) longest-prefix-match
+/// path used when every entry of an `Or` subsection is a non-negated address entry.
+pub fn optimize_ipranges(relation: Relation, entries: Vec<GlobalFilterEntry>) -> Vec<GlobalFilterEntry> {
+    let mut seen: HashSet<(bool, String)> = HashSet::new();
+    let mut out = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let key = match &entry.entry {
+            GlobalFilterEntryE::Ip(ip) => Some(ip.to_string()),
+            GlobalFilterEntryE::Network(net) => Some(net.to_string()),
+            GlobalFilterEntryE::Range4(net) => Some(net.to_string()),
+            GlobalFilterEntryE::Range6(net) => Some(net.to_string()),
+            _ => None,
+        };
+        match key {
+            Some(k) if !seen.insert((entry.negated, k)) => continue,
+            _ => out.push(entry),
+        }
+    }
+    // the relation itself doesn't change the dedup logic above, but is kept as a parameter so
+    // future optimizations (e.g. collapsing an all-Or run of adjacent /N networks) can use it
+    let _ = relation;
+    out
+}
@@ -0,0 +1,148 @@
+/// A binary radix trie (one node per address bit; chains of single-child nodes are not compressed) used to turn large CIDR lists (blocklists, ASN expansions)
+/// into O(address-length) membership tests instead of the O(n) linear scan `check_entry` does
+/// for every `GlobalFilterEntryE::Ip/Network/Range4/Range6` entry.
+///
+/// IPv4 and IPv6 are stored in separate tries (`v4`/`v6`). Each node represents a shared
+/// bit-prefix; inserting a CIDR walks/branches on successive bits up to the prefix length and
+/// marks the terminal node with whether the entry is a positive match or a `!network`
+/// subtraction. Lookup walks the bits of the request IP and remembers the *deepest* marked node
+/// seen — longest-prefix-match wins, and a negative entry at that depth overrides any shallower
+/// positive one.
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+#[derive(Debug, Default, Clone)]
+struct Node {
+    mark: Option<bool>, // Some(true) = positive entry, Some(false) = negated (subtractive) entry
+    children: [Option<Box<Node>>; 2],
+}
+
+impl Node {
+    fn insert(&mut self, bits: impl Iterator<Item = bool>, positive: bool) {
+        let mut node = self;
+        for bit in bits {
+            node = node.children[bit as usize].get_or_insert_with(|| Box::new(Node::default()));
+        }
+        node.mark = Some(positive);
+    }
+
+    fn lookup(&self, bits: impl Iterator<Item = bool>) -> Option<bool> {
+        let mut node = self;
+        let mut deepest = node.mark;
+        for bit in bits {
+            match &node.children[bit as usize] {
+                None => break,
+                Some(child) => {
+                    node = child;
+                    if node.mark.is_some() {
+                        deepest = node.mark;
+                    }
+                }
+            }
+        }
+        deepest
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct NetworkMatcher {
+    v4: Node,
+    v6: Node,
+}
+
+fn v4_bits(addr: Ipv4Addr, prefix_len: u8) -> impl Iterator<Item = bool> {
+    let bits = u32::from(addr);
+    (0..prefix_len).map(move |i| (bits >> (31 - i)) & 1 == 1)
+}
+
+fn v6_bits(addr: Ipv6Addr, prefix_len: u8) -> impl Iterator<Item = bool> {
+    let bits = u128::from(addr);
+    (0..prefix_len).map(move |i| (bits >> (127 - i)) & 1 == 1)
+}
+
+impl NetworkMatcher {
+    pub fn insert_v4(&mut self, addr: Ipv4Addr, prefix_len: u8, positive: bool) {
+        self.v4.insert(v4_bits(addr, prefix_len), positive);
+    }
+
+    pub fn insert_v6(&mut self, addr: Ipv6Addr, prefix_len: u8, positive: bool) {
+        self.v6.insert(v6_bits(addr, prefix_len), positive);
+    }
+
+    /// Returns `Some(true)` if the deepest (longest-prefix) entry matching `ip` is a positive
+    /// entry, `Some(false)` if it's a `!network` subtraction, or `None` if nothing matched.
+    pub fn lookup(&self, ip: IpAddr) -> Option<bool> {
+        match ip {
+            IpAddr::V4(v4) => self.v4.lookup(v4_bits(v4, 32)),
+            IpAddr::V6(v6) => self.v6.lookup(v6_bits(v6, 128)),
+        }
+    }
+}
+
+impl NetworkMatcher {
+    /// Builds a matcher for a subsection's entries, if and only if every entry is a
+    /// non-negated IP/Network/Range4/Range6 entry and the subsection relation is `Or` — an
+    /// `And` (or a mix with non-address entries) can't be represented as "deepest mark wins",
+    /// so callers should fall back to the linear `check_entry` path in that case.
+    ///
+    /// Negated entries (`!network`) are also left to the linear path: `check_entry` applies
+    /// `!network` as "matches whenever the IP is *not* in that network" and OR-combines the
+    /// per-entry results, which is a different operation from this trie's longest-prefix
+    /// carve-out (deepest mark wins, negative mark means "not matched"). Building a trie for a
+    /// subsection containing a negated entry would silently flip allow/deny decisions.
+    pub fn try_build(relation: crate::config::raw::Relation, entries: &[crate::config::globalfilter::GlobalFilterEntry]) -> Option<Self> {
+        use crate::config::globalfilter::GlobalFilterEntryE::*;
+        use crate::config::raw::Relation;
+        if relation != Relation::Or {
+            return None;
+        }
+        let mut matcher = NetworkMatcher::default();
+        for entry in entries {
+            if entry.negated {
+                return None;
+            }
+            match &entry.entry {
+                Ip(IpAddr::V4(addr)) => matcher.insert_v4(*addr, 32, true),
+                Ip(IpAddr::V6(addr)) => matcher.insert_v6(*addr, 128, true),
+                Network(ipnet::IpNet::V4(net)) => matcher.insert_v4(net.network(), net.prefix_len(), true),
+                Network(ipnet::IpNet::V6(net)) => matcher.insert_v6(net.network(), net.prefix_len(), true),
+                Range4(net) => matcher.insert_v4(net.network(), net.prefix_len(), true),
+                Range6(net) => matcher.insert_v6(net.network(), net.prefix_len(), true),
+                _ => return None,
+            }
+        }
+        Some(matcher)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longest_prefix_wins() {
+        let mut m = NetworkMatcher::default();
+        m.insert_v4("10.0.0.0".parse().unwrap(), 8, true);
+        m.insert_v4("10.1.0.0".parse().unwrap(), 16, false);
+        assert_eq!(m.lookup("10.2.3.4".parse().unwrap()), Some(true));
+        assert_eq!(m.lookup("10.1.3.4".parse().unwrap()), Some(false));
+        assert_eq!(m.lookup("11.0.0.0".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn v4_and_v6_are_independent() {
+        let mut m = NetworkMatcher::default();
+        m.insert_v4("192.168.0.0".parse().unwrap(), 16, true);
+        assert_eq!(m.lookup("::1".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn try_build_rejects_negated_entries() {
+        use crate::config::globalfilter::{GlobalFilterEntry, GlobalFilterEntryE};
+        use crate::config::raw::Relation;
+        let entries = vec![GlobalFilterEntry {
+            negated: true,
+            entry: GlobalFilterEntryE::Network("10.0.0.0/8".parse().unwrap()),
+        }];
+        assert!(NetworkMatcher::try_build(Relation::Or, &entries).is_none());
+    }
+}
@@ -0,0 +1,85 @@
+/// Hot-reloadable configuration: WAF profiles/signatures and global filter sections behind a
+/// single atomically-swapped snapshot, so callers never observe a torn mix of old and new config
+/// while a reload is in flight, and rebuilding the (expensive) hyperscan `VectoredDatabase` never
+/// blocks requests still being served by the previous one.
+///
+/// Callers fetch a snapshot with `current()` once per request (or once per reload-watch tick) and
+/// read `.waf_profiles`/`.waf_signatures`/`.globalfilters` off it. `tag_request` grabs its own
+/// snapshot this way, so a reload landing mid-request can't hand it a torn mix of old and new
+/// global filters.
+use crate::config::globalfilter::GlobalFilterSection;
+use crate::config::raw::{RawWafProfile, WafSignature};
+use crate::config::waf::{resolve_signatures, WafProfile, WafSignatures};
+use crate::logs::Logs;
+use arc_swap::ArcSwap;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// A fully-resolved, internally-consistent set of config, versioned so in-flight requests can be
+/// correlated with the generation that produced their decision.
+pub struct ConfigSnapshot {
+    pub version: u64,
+    pub waf_profiles: HashMap<String, WafProfile>,
+    pub waf_signatures: Arc<WafSignatures>,
+    pub globalfilters: Vec<GlobalFilterSection>,
+}
+
+impl Default for ConfigSnapshot {
+    fn default() -> Self {
+        ConfigSnapshot {
+            version: 0,
+            waf_profiles: HashMap::new(),
+            waf_signatures: Arc::new(WafSignatures::empty()),
+            globalfilters: Vec::new(),
+        }
+    }
+}
+
+static CURRENT: Lazy<ArcSwap<ConfigSnapshot>> = Lazy::new(|| ArcSwap::from_pointee(ConfigSnapshot::default()));
+
+/// Returns the config snapshot in effect right now. Cheap (an `Arc` clone), so callers should grab
+/// one reference per request rather than re-reading it repeatedly mid-request.
+pub fn current() -> Arc<ConfigSnapshot> {
+    CURRENT.load_full()
+}
+
+/// Resolves `raw_waf`/`raw_signatures` and, with the already-resolved `globalfilters` (stored as
+/// given), builds a new snapshot off the hot path and
+/// atomically swaps it in, but only if resolution produced no errors. On failure, the previous
+/// snapshot is left in place (so in-flight and new requests keep seeing a known-good config) and
+/// `false` is returned; `logs` carries the reason either way and should be surfaced to the
+/// operator regardless of the outcome.
+pub fn try_reload(
+    logs: &mut Logs,
+    raw_waf: Vec<RawWafProfile>,
+    raw_signatures: Vec<WafSignature>,
+    globalfilters: Vec<GlobalFilterSection>,
+) -> bool {
+    let expected_profiles = raw_waf.len();
+    let waf_profiles = WafProfile::resolve(logs, raw_waf);
+    if waf_profiles.len() != expected_profiles {
+        logs.error(format!(
+            "waf profile resolution produced {} of {} expected profiles, keeping previous config",
+            waf_profiles.len(),
+            expected_profiles
+        ));
+        return false;
+    }
+    let waf_signatures = match resolve_signatures(raw_signatures) {
+        Ok(sigs) => sigs,
+        Err(rr) => {
+            logs.error(format!("waf signature compilation failed, keeping previous config: {:?}", rr));
+            return false;
+        }
+    };
+    let previous = CURRENT.load();
+    let snapshot = ConfigSnapshot {
+        version: previous.version + 1,
+        waf_profiles,
+        waf_signatures: Arc::new(waf_signatures),
+        globalfilters,
+    };
+    CURRENT.store(Arc::new(snapshot));
+    true
+}
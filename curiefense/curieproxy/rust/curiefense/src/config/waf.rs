@@ -33,6 +33,117 @@ impl Transformation {
     ];
 }
 
+/// Implements RFC 3986 §5.2.4 `remove_dot_segments`, plus normalizing percent-encoding
+/// (uppercase hex digits, decoding unreserved characters) and collapsing repeated slashes. Used
+/// as a pre-match canonicalizer ahead of global filter `Path`/`Query`/`Uri` matching, so `/./`,
+/// `/../`, duplicate slashes, and inconsistent percent-encoding can't be used to evade a rule
+/// written against the canonical form.
+///
+/// Not currently wired into the WAF `path` section match (the content-filter transform pipeline
+/// that would apply it lives outside this module) — a WAF profile's `path` section still matches
+/// `qinfo.qpath` raw.
+pub fn normalize_path(path: &str) -> String {
+    remove_dot_segments(&collapse_slashes(&normalize_percent_encoding(path)))
+}
+
+fn hex_val(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+fn is_unreserved(b: u8) -> bool {
+    matches!(b, b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~')
+}
+
+fn normalize_percent_encoding(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut out = String::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let (Some(hi), Some(lo)) = (hex_val(bytes[i + 1]), hex_val(bytes[i + 2])) {
+                let value = (hi << 4) | lo;
+                if is_unreserved(value) {
+                    out.push(value as char);
+                } else {
+                    out.push('%');
+                    out.push(bytes[i + 1].to_ascii_uppercase() as char);
+                    out.push(bytes[i + 2].to_ascii_uppercase() as char);
+                }
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i] as char);
+        i += 1;
+    }
+    out
+}
+
+fn collapse_slashes(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    let mut prev_slash = false;
+    for c in path.chars() {
+        if c == '/' {
+            if prev_slash {
+                continue;
+            }
+            prev_slash = true;
+        } else {
+            prev_slash = false;
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn remove_dot_segments(path: &str) -> String {
+    let mut input = path.to_string();
+    let mut output = String::with_capacity(path.len());
+    while !input.is_empty() {
+        if input.starts_with("../") {
+            input.drain(..3);
+        } else if input.starts_with("./") {
+            input.drain(..2);
+        } else if input.starts_with("/./") {
+            input = format!("/{}", &input[3..]);
+        } else if input == "/." {
+            input = "/".to_string();
+        } else if input.starts_with("/../") {
+            input = format!("/{}", &input[4..]);
+            remove_last_segment(&mut output);
+        } else if input == "/.." {
+            input = "/".to_string();
+            remove_last_segment(&mut output);
+        } else if input == "." || input == ".." {
+            input.clear();
+        } else {
+            // move the first path segment (including the leading '/', if any) to output
+            let seg_end = if let Some(rest) = input.strip_prefix('/') {
+                rest.find('/').map(|p| p + 1).unwrap_or(input.len())
+            } else {
+                input.find('/').unwrap_or(input.len())
+            };
+            output.push_str(&input[..seg_end]);
+            input.drain(..seg_end);
+        }
+    }
+    output
+}
+
+/// Removes the last path segment (and its preceding `/`, if any) from `output`, used when
+/// folding a `/../` (or trailing `/..`) back in `remove_dot_segments`.
+fn remove_last_segment(output: &mut String) {
+    match output.rfind('/') {
+        Some(pos) => output.truncate(pos),
+        None => output.clear(),
+    }
+}
+
 // TODO: undefined data structures
 #[derive(Debug, Clone)]
 pub struct WafProfile {
@@ -255,3 +366,33 @@ pub fn resolve_signatures(raws: Vec<WafSignature>) -> anyhow::Result<WafSignatur
         ids: raws,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::normalize_path;
+
+    #[test]
+    fn removes_dot_segments() {
+        assert_eq!(normalize_path("/a/b/../c"), "/a/c");
+        assert_eq!(normalize_path("/a/./b"), "/a/b");
+        assert_eq!(normalize_path("/../a"), "/a");
+        assert_eq!(normalize_path("/a/.."), "/");
+    }
+
+    #[test]
+    fn collapses_repeated_slashes() {
+        assert_eq!(normalize_path("/a//b///c"), "/a/b/c");
+    }
+
+    #[test]
+    fn normalizes_percent_encoding() {
+        assert_eq!(normalize_path("/admin%2e%2e/secret"), "/admin../secret");
+        assert_eq!(normalize_path("/%7euser"), "/~user");
+        assert_eq!(normalize_path("/%2f"), "/%2F");
+    }
+
+    #[test]
+    fn combines_all_passes() {
+        assert_eq!(normalize_path("/a//%2e%2e//b"), "/b");
+    }
+}
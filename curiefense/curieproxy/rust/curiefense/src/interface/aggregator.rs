@@ -0,0 +1,184 @@
+/// Accumulates per-decision statistics in memory and exposes them both as the aggregates baked
+/// into the JSON log, and as a Prometheus text-exposition endpoint so Curiefense can be scraped
+/// directly without a log pipeline.
+use crate::interface::{BlockReason, Decision, InitiatorKind, Tags};
+use crate::utils::RequestInfo;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+
+/// Distinct (secpolid, secpolentryid) labels above this count collapse into the `other` bucket,
+/// so a long-lived process with churning/ephemeral security policy ids doesn't grow its
+/// cardinality without bound.
+const MAX_SECPOL_LABELS: usize = 256;
+const OTHER_LABEL: &str = "other";
+
+#[derive(Default)]
+struct SecpolCounters {
+    total: AtomicU64,
+    blocked: AtomicU64,
+    passed: AtomicU64,
+    bytes_sent: AtomicU64,
+    status_class: [AtomicU64; 6], // 1xx..5xx + unknown
+    triggers: [AtomicU64; 5],     // acl, gf, rl, cf, cf_restrict
+}
+
+static COUNTERS: Lazy<Mutex<HashMap<(String, String), SecpolCounters>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn status_class_idx(code: Option<u32>) -> usize {
+    match code {
+        Some(c) if (100..600).contains(&c) => (c / 100) as usize - 1,
+        _ => 5,
+    }
+}
+
+fn trigger_idx(kind: &InitiatorKind) -> usize {
+    match kind {
+        InitiatorKind::Acl => 0,
+        InitiatorKind::GlobalFilter => 1,
+        InitiatorKind::RateLimit => 2,
+        InitiatorKind::ContentFilter => 3,
+        InitiatorKind::Restriction => 4,
+    }
+}
+
+fn label_for(secpolid: &str, secpolentryid: &str, known: &HashMap<(String, String), SecpolCounters>) -> (String, String) {
+    let key = (secpolid.to_string(), secpolentryid.to_string());
+    if known.contains_key(&key) || known.len() < MAX_SECPOL_LABELS {
+        key
+    } else {
+        (OTHER_LABEL.to_string(), OTHER_LABEL.to_string())
+    }
+}
+
+/// Records one decision. Called once per request from `jsonlog`, right after the return code
+/// becomes available.
+pub async fn aggregate(dec: &Decision, rcode: Option<u32>, rinfo: &RequestInfo, _tags: &Tags, bytes_sent: Option<u64>) {
+    let secpolid = rinfo.rinfo.secpolicy.policy.id.as_str();
+    let secpolentryid = rinfo.rinfo.secpolicy.entry.id.as_str();
+
+    let greasons = BlockReason::regroup(&dec.reasons);
+
+    let mut map = COUNTERS.lock().unwrap_or_else(|p| p.into_inner());
+    let label = label_for(secpolid, secpolentryid, &map);
+    let counters = map.entry(label).or_default();
+
+    counters.total.fetch_add(1, Ordering::Relaxed);
+    if dec.is_blocking() {
+        counters.blocked.fetch_add(1, Ordering::Relaxed);
+    } else {
+        counters.passed.fetch_add(1, Ordering::Relaxed);
+    }
+    counters.status_class[status_class_idx(rcode)].fetch_add(1, Ordering::Relaxed);
+    counters.bytes_sent.fetch_add(bytes_sent.unwrap_or(0), Ordering::Relaxed);
+    for (kind, reasons) in greasons.iter() {
+        counters.triggers[trigger_idx(kind)].fetch_add(reasons.len() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Drops every accumulated counter. Exposed so a periodic task can decay long-lived processes
+/// (e.g. once a day) instead of letting secpol churn grow the `other` bucket forever.
+pub fn reset() {
+    COUNTERS.lock().unwrap_or_else(|p| p.into_inner()).clear();
+}
+
+fn write_metric(out: &mut String, name: &str, help: &str, mtype: &str, rows: &[(Vec<(&str, &str)>, u64)]) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, mtype);
+    for (labels, value) in rows {
+        let labelstr = labels
+            .iter()
+            .map(|(k, v)| format!("{}=\"{}\"", k, v.replace('\\', "\\\\").replace('"', "\\\"")))
+            .collect::<Vec<_>>()
+            .join(",");
+        let _ = writeln!(out, "{}{{{}}} {}", name, labelstr, value);
+    }
+}
+
+const TRIGGER_KINDS: [(&str, usize); 5] = [
+    ("acl", 0),
+    ("gf", 1),
+    ("rl", 2),
+    ("cf", 3),
+    ("cf_restrict", 4),
+];
+const STATUS_CLASSES: [(&str, usize); 6] = [
+    ("1xx", 0),
+    ("2xx", 1),
+    ("3xx", 2),
+    ("4xx", 3),
+    ("5xx", 4),
+    ("unknown", 5),
+];
+
+/// Serializes the accumulated counters into Prometheus text-exposition format.
+pub fn render_prometheus() -> String {
+    let map = COUNTERS.lock().unwrap_or_else(|p| p.into_inner());
+
+    let mut totals = Vec::new();
+    let mut blocked = Vec::new();
+    let mut passed = Vec::new();
+    let mut bytes = Vec::new();
+    let mut status_rows = Vec::new();
+    let mut trigger_rows = Vec::new();
+
+    for ((secpolid, secpolentryid), c) in map.iter() {
+        let labels = vec![("secpolid", secpolid.as_str()), ("secpolentryid", secpolentryid.as_str())];
+        totals.push((labels.clone(), c.total.load(Ordering::Relaxed)));
+        blocked.push((labels.clone(), c.blocked.load(Ordering::Relaxed)));
+        passed.push((labels.clone(), c.passed.load(Ordering::Relaxed)));
+        bytes.push((labels.clone(), c.bytes_sent.load(Ordering::Relaxed)));
+        for (name, idx) in STATUS_CLASSES {
+            let mut l = labels.clone();
+            l.push(("status_class", name));
+            status_rows.push((l, c.status_class[idx].load(Ordering::Relaxed)));
+        }
+        for (name, idx) in TRIGGER_KINDS {
+            let mut l = labels.clone();
+            l.push(("kind", name));
+            trigger_rows.push((l, c.triggers[idx].load(Ordering::Relaxed)));
+        }
+    }
+
+    let mut out = String::new();
+    write_metric(&mut out, "curiefense_requests_total", "Total requests seen", "counter", &totals);
+    write_metric(
+        &mut out,
+        "curiefense_requests_blocked_total",
+        "Requests that resulted in a blocking action",
+        "counter",
+        &blocked,
+    );
+    write_metric(
+        &mut out,
+        "curiefense_requests_passed_total",
+        "Requests that were not blocked",
+        "counter",
+        &passed,
+    );
+    write_metric(
+        &mut out,
+        "curiefense_bytes_sent_total",
+        "Bytes sent back to the client",
+        "counter",
+        &bytes,
+    );
+    write_metric(
+        &mut out,
+        "curiefense_responses_total",
+        "Responses by status class",
+        "counter",
+        &status_rows,
+    );
+    write_metric(
+        &mut out,
+        "curiefense_triggers_total",
+        "Block reasons by initiator kind",
+        "counter",
+        &trigger_rows,
+    );
+    out
+}
@@ -5,7 +5,9 @@ use crate::config::raw::{RawAction, RawActionType};
 use crate::grasshopper::{challenge_phase01, GHMode, Grasshopper, PrecisionLevel};
 use crate::logs::Logs;
 use crate::utils::json::NameValue;
-use crate::utils::templating::{parse_request_template, RequestTemplate, TVar, TemplatePart};
+use crate::utils::templating::{
+    parse_request_template, RequestMap, RequestTemplate, SectionKind, TVar, TemplateHelper, TemplatePart,
+};
 use crate::utils::{selector, GeoIp, RequestInfo, Selected};
 use serde::ser::{SerializeMap, SerializeSeq};
 use serde::{Deserialize, Serialize, Serializer};
@@ -17,6 +19,7 @@ pub use self::tagging::*;
 
 pub mod aggregator;
 pub mod block_reasons;
+pub mod otel;
 pub mod stats;
 pub mod tagging;
 
@@ -202,6 +205,7 @@ pub async fn jsonlog(
     match mrinfo {
         Some(rinfo) => {
             aggregator::aggregate(dec, status_code, rinfo, tags, bytes_sent).await;
+            otel::record_decision(dec, rinfo, tags, stats, status_code);
             match jsonlog_rinfo(dec, rinfo, status_code, tags, stats, logs, proxy, &now) {
                 Err(_) => (b"null".to_vec(), now),
                 Ok(y) => (y, now),
@@ -531,6 +535,10 @@ impl SimpleActionT {
 pub struct SimpleAction {
     pub atype: SimpleActionT,
     pub headers: Option<HashMap<String, RequestTemplate>>,
+    /// Alternative block bodies, picked at decision time by matching the request `Accept`
+    /// header against each `MediaType`. Empty/absent falls back to `SimpleActionT::Custom`'s
+    /// plain `content`.
+    pub bodies: Vec<(MediaType, RequestTemplate)>,
     pub status: u32,
     pub extra_tags: Option<HashSet<String>>,
 }
@@ -540,12 +548,64 @@ impl Default for SimpleAction {
         SimpleAction {
             atype: SimpleActionT::default(),
             headers: None,
+            bodies: Vec::new(),
             status: 503,
             extra_tags: None,
         }
     }
 }
 
+/// A content type a block body can be rendered as, matched against the request's `Accept`
+/// header by `pick_body`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaType {
+    Json,
+    Xml,
+    Html,
+    PlainText,
+}
+
+impl MediaType {
+    pub fn mime(&self) -> &'static str {
+        match self {
+            MediaType::Json => "application/json",
+            MediaType::Xml => "application/xml",
+            MediaType::Html => "text/html",
+            MediaType::PlainText => "text/plain",
+        }
+    }
+
+    pub fn from_mime(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "application/json" | "text/json" => Some(MediaType::Json),
+            "application/xml" | "text/xml" => Some(MediaType::Xml),
+            "text/html" | "application/xhtml+xml" => Some(MediaType::Html),
+            "text/plain" => Some(MediaType::PlainText),
+            _ => None,
+        }
+    }
+
+    /// The escaping `render_body_template` applies to each `{{ ... }}` substitution by default,
+    /// so a header/arg/cookie value containing `"` or `<`/`>` can't break the document's syntax.
+    fn escaping(&self) -> Escaping {
+        match self {
+            MediaType::Json => Escaping::Json,
+            MediaType::Xml | MediaType::Html => Escaping::Xml,
+            MediaType::PlainText => Escaping::None,
+        }
+    }
+}
+
+/// How `render_body_template` escapes a substituted value before splicing it into the raw
+/// template text around it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Escaping {
+    /// Used for header templates and `text/plain` bodies: substitutions are inserted unescaped.
+    None,
+    Json,
+    Xml,
+}
+
 impl Default for SimpleActionT {
     fn default() -> Self {
         SimpleActionT::Custom {
@@ -635,6 +695,13 @@ impl SimpleAction {
         } else {
             Some(rawaction.tags.iter().cloned().collect())
         };
+        let bodies = rawaction
+            .params
+            .bodies
+            .iter()
+            .flatten()
+            .filter_map(|(mime, tmpl)| MediaType::from_mime(mime).map(|mt| (mt, parse_request_template(tmpl))))
+            .collect();
 
         Ok((
             id,
@@ -642,6 +709,7 @@ impl SimpleAction {
                 atype,
                 status,
                 headers,
+                bodies,
                 extra_tags,
             },
         ))
@@ -670,6 +738,13 @@ impl SimpleAction {
             SimpleActionT::Custom { content } => {
                 action.atype = ActionType::Block;
                 action.content = content.clone();
+                if let Some((mtype, tmpl)) = pick_body(rinfo, &self.bodies) {
+                    action.content = render_body_template(rinfo, tags, tmpl, mtype.escaping());
+                    action
+                        .headers
+                        .get_or_insert_with(HashMap::new)
+                        .insert("content-type".to_string(), mtype.mime().to_string());
+                }
             }
             SimpleActionT::Challenge { ch_level } => {
                 let is_human = match ch_level {
@@ -734,24 +809,285 @@ impl SimpleAction {
     }
 }
 
+/// Picks which configured body to render based on the request's `Accept` header: highest
+/// `q`-value match wins, `*/*`/`type/*` fall back to the first body of a matching/any type, and
+/// an absent header, or one with no entries, picks the first configured body. When no entry with
+/// a positive `q`-value matches a configured body, returns `None` (the action then keeps its plain
+/// `content`).
+fn pick_body<'a>(rinfo: &RequestInfo, bodies: &'a [(MediaType, RequestTemplate)]) -> Option<&'a (MediaType, RequestTemplate)> {
+    if bodies.is_empty() {
+        return None;
+    }
+    let accept = match rinfo.headers.get("accept") {
+        Some(accept) => accept,
+        None => return bodies.first(),
+    };
+    let mut prefs: Vec<(&str, f32)> = accept
+        .split(',')
+        .filter_map(|part| {
+            let part = part.trim();
+            if part.is_empty() {
+                return None;
+            }
+            let mut it = part.split(';');
+            let mime = it.next()?.trim();
+            let q = it
+                .find_map(|p| p.trim().strip_prefix("q=").and_then(|v| v.parse::<f32>().ok()))
+                .unwrap_or(1.0);
+            Some((mime, q))
+        })
+        .collect();
+    if prefs.is_empty() {
+        return bodies.first();
+    }
+    prefs.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+    for (mime, q) in prefs {
+        if q <= 0.0 {
+            continue;
+        }
+        if mime == "*/*" {
+            return bodies.first();
+        }
+        if let Some(found) = bodies.iter().find(|(mt, _)| mt.mime().eq_ignore_ascii_case(mime)) {
+            return Some(found);
+        }
+        if let Some(major) = mime.strip_suffix("/*") {
+            if let Some(found) = bodies
+                .iter()
+                .find(|(mt, _)| mt.mime().split('/').next() == Some(major))
+            {
+                return Some(found);
+            }
+        }
+    }
+    None
+}
+
 fn render_template(rinfo: &RequestInfo, tags: &Tags, template: &[TemplatePart<TVar>]) -> String {
+    render_template_with(rinfo, tags, template, None, Escaping::None)
+}
+
+/// Renders a block body, escaping each `{{ ... }}` substitution per `escaping` so the emitted
+/// document stays syntactically valid even when a header/arg/cookie value contains a `"` or a
+/// `<`/`>`. An explicit `jsonstring`/`xmlstring` helper call is trusted as-is and not
+/// double-escaped.
+fn render_body_template(rinfo: &RequestInfo, tags: &Tags, template: &[TemplatePart<TVar>], escaping: Escaping) -> String {
+    render_template_with(rinfo, tags, template, None, escaping)
+}
+
+/// `loop_value` is the binding introduced by an enclosing `{{#each}}`, if any, made available
+/// inside the loop body as the special `this` variable.
+fn render_template_with(
+    rinfo: &RequestInfo,
+    tags: &Tags,
+    template: &[TemplatePart<TVar>],
+    loop_value: Option<&str>,
+    escaping: Escaping,
+) -> String {
     let mut out = String::new();
     for p in template {
         match p {
             TemplatePart::Raw(s) => out.push_str(s),
-            TemplatePart::Var(TVar::Selector(RequestSelector::Tags)) => {
-                out.push_str(&serde_json::to_string(&tags).unwrap_or_else(|_| "null".into()))
+            TemplatePart::Section {
+                kind: SectionKind::If,
+                subject,
+                body,
+                alt,
+            } => {
+                let branch = if is_truthy(rinfo, tags, subject) { body } else { alt };
+                out.push_str(&render_template_with(rinfo, tags, branch, loop_value, escaping));
             }
-            TemplatePart::Var(TVar::Tag(tagname)) => {
-                out.push_str(if tags.contains(tagname) { "true" } else { "false" })
+            TemplatePart::Section {
+                kind: SectionKind::Unless,
+                subject,
+                body,
+                alt,
+            } => {
+                let branch = if is_truthy(rinfo, tags, subject) { alt } else { body };
+                out.push_str(&render_template_with(rinfo, tags, branch, loop_value, escaping));
             }
-            TemplatePart::Var(TVar::Selector(sel)) => match selector(rinfo, sel, Some(tags)) {
-                None => out.push_str("nil"),
-                Some(Selected::OStr(s)) => out.push_str(&s),
-                Some(Selected::Str(s)) => out.push_str(s),
-                Some(Selected::U32(v)) => out.push_str(&v.to_string()),
-            },
+            TemplatePart::Section {
+                kind: SectionKind::Each,
+                subject,
+                body,
+                ..
+            } => {
+                for item in each_items(rinfo, tags, subject) {
+                    out.push_str(&render_template_with(rinfo, tags, body, Some(&item), escaping));
+                }
+            }
+            TemplatePart::Var(v) => out.push_str(&escape_var(escaping, v, render_var(rinfo, tags, loop_value, v))),
         }
     }
     out
 }
+
+/// Applies `escaping` to a rendered `TVar`'s output, unless `v` is itself an explicit
+/// `jsonstring`/`xmlstring` helper call (the author already produced safely-embeddable text).
+fn escape_var(escaping: Escaping, v: &TVar, rendered: String) -> String {
+    if matches!(
+        v,
+        TVar::Helper {
+            name: TemplateHelper::JsonString | TemplateHelper::XmlString,
+            ..
+        }
+    ) {
+        return rendered;
+    }
+    match escaping {
+        Escaping::None => rendered,
+        Escaping::Json => json_escape(&rendered),
+        Escaping::Xml => xmlescape(&rendered),
+    }
+}
+
+/// Escapes `"`, `\`, and control characters so `rendered` can be embedded inside an existing
+/// JSON string literal, without the surrounding quotes `TemplateHelper::JsonString` adds.
+fn json_escape(input: &str) -> String {
+    let quoted = serde_json::to_string(input).unwrap_or_else(|_| "\"\"".to_string());
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+fn render_var(rinfo: &RequestInfo, tags: &Tags, loop_value: Option<&str>, v: &TVar) -> String {
+    match v {
+        TVar::Tag(tagname) if tagname == "this" => loop_value.unwrap_or("nil").to_string(),
+        TVar::Selector(RequestSelector::Tags) => serde_json::to_string(&tags).unwrap_or_else(|_| "null".into()),
+        TVar::Tag(tagname) => (if tags.contains(tagname) { "true" } else { "false" }).to_string(),
+        TVar::Selector(sel) => match selector(rinfo, sel, Some(tags)) {
+            None => "nil".to_string(),
+            Some(Selected::OStr(s)) => s.to_string(),
+            Some(Selected::Str(s)) => s.to_string(),
+            Some(Selected::U32(v)) => v.to_string(),
+        },
+        TVar::Helper { name, arg } => {
+            if let TemplateHelper::Default(fallback) = name {
+                if is_nil(rinfo, tags, arg) {
+                    return fallback.clone();
+                }
+            }
+            apply_helper(name, &render_var(rinfo, tags, loop_value, arg))
+        }
+        TVar::Lookup { map, key } => {
+            let key = render_var(rinfo, tags, loop_value, key);
+            lookup_map(rinfo, *map, &key).unwrap_or_else(|| "nil".to_string())
+        }
+    }
+}
+
+fn lookup_map(rinfo: &RequestInfo, map: RequestMap, key: &str) -> Option<String> {
+    match map {
+        RequestMap::Headers => rinfo.headers.get(key).cloned(),
+        RequestMap::Cookies => rinfo.cookies.get(key).cloned(),
+        RequestMap::Args => rinfo.rinfo.qinfo.args.get(key).cloned(),
+        RequestMap::PathSegments => rinfo.rinfo.qinfo.path_as_map.get(key).cloned(),
+    }
+}
+
+/// `true` when `v` resolves to `nil` (an unset tag, or a selector that doesn't resolve), used by
+/// the `default:<literal>` helper.
+fn is_nil(rinfo: &RequestInfo, tags: &Tags, v: &TVar) -> bool {
+    match v {
+        TVar::Tag(tagname) => !tags.contains(tagname),
+        TVar::Selector(sel) => selector(rinfo, sel, Some(tags)).is_none(),
+        TVar::Helper { .. } => false,
+        TVar::Lookup { map, key } => lookup_map(rinfo, *map, &render_var(rinfo, tags, None, key)).is_none(),
+    }
+}
+
+fn apply_helper(helper: &TemplateHelper, input: &str) -> String {
+    match helper {
+        TemplateHelper::Lower => input.to_lowercase(),
+        TemplateHelper::Upper => input.to_uppercase(),
+        TemplateHelper::Title => input
+            .split_whitespace()
+            .map(|w| {
+                let mut c = w.chars();
+                match c.next() {
+                    None => String::new(),
+                    Some(first) => first.to_uppercase().collect::<String>() + &c.as_str().to_lowercase(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+        TemplateHelper::Sha256 => {
+            use sha2::{Digest, Sha256};
+            let mut hasher = Sha256::new();
+            hasher.update(input.as_bytes());
+            hex::encode(hasher.finalize())
+        }
+        TemplateHelper::Md5 => format!("{:x}", md5::compute(input.as_bytes())),
+        TemplateHelper::UrlEncode => urlencode(input),
+        TemplateHelper::JsonString => serde_json::to_string(input).unwrap_or_else(|_| "\"\"".to_string()),
+        TemplateHelper::XmlString => xmlescape(input),
+        // the fallback substitution itself already happened in render_var; a non-nil value just
+        // passes through unchanged
+        TemplateHelper::Default(_) => input.to_string(),
+    }
+}
+
+fn xmlescape(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn urlencode(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for b in input.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+/// Truthiness used by `{{#if}}`/`{{#unless}}`: a tag is truthy when present, a selector is
+/// truthy when it resolves to a non-`nil`, non-empty value.
+fn is_truthy(rinfo: &RequestInfo, tags: &Tags, subject: &TVar) -> bool {
+    match subject {
+        TVar::Tag(tagname) => tags.contains(tagname),
+        TVar::Selector(sel) => match selector(rinfo, sel, Some(tags)) {
+            None => false,
+            Some(Selected::OStr(s)) => !s.is_empty(),
+            Some(Selected::Str(s)) => !s.is_empty(),
+            Some(Selected::U32(_)) => true,
+        },
+        TVar::Helper { .. } | TVar::Lookup { .. } => {
+            !is_nil(rinfo, tags, subject) && !render_var(rinfo, tags, None, subject).is_empty()
+        }
+    }
+}
+
+/// Values bound in turn by `{{#each}}`: every tag name for `TVar::Selector(Tags)`, or each
+/// element of an array-valued selector.
+fn each_items(rinfo: &RequestInfo, tags: &Tags, subject: &TVar) -> Vec<String> {
+    match subject {
+        TVar::Tag(tagname) if tagname == "tags" => tags.inner().keys().cloned().collect(),
+        TVar::Selector(RequestSelector::Tags) => tags.inner().keys().cloned().collect(),
+        TVar::Tag(_) => Vec::new(),
+        TVar::Selector(sel) => match selector(rinfo, sel, Some(tags)) {
+            None => Vec::new(),
+            Some(Selected::OStr(s)) => vec![s.to_string()],
+            Some(Selected::Str(s)) => vec![s.to_string()],
+            Some(Selected::U32(v)) => vec![v.to_string()],
+        },
+        TVar::Helper { .. } | TVar::Lookup { .. } => {
+            if is_nil(rinfo, tags, subject) {
+                Vec::new()
+            } else {
+                vec![render_var(rinfo, tags, None, subject)]
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+/// Optional OpenTelemetry export: request spans + metric instruments mirroring `jsonlog_rinfo`.
+///
+/// This subsystem is opt-in: unless `init` has been called (typically once at startup, from an
+/// endpoint read out of the operator config), `record_decision` is a no-op so the existing JSON
+/// log path is completely unaffected.
+use crate::interface::{ActionType, BlockReason, Decision, Stats, Tags};
+use crate::utils::RequestInfo;
+use opentelemetry::metrics::{Counter, Histogram, Meter};
+use opentelemetry::trace::{Span, Tracer};
+use opentelemetry::{global, KeyValue};
+use once_cell::sync::OnceCell;
+
+static OTEL: OnceCell<OtelHandles> = OnceCell::new();
+
+struct OtelHandles {
+    decisions_total: Counter<u64>,
+    stage_timing: Histogram<f64>,
+}
+
+/// Initializes the global OTLP exporters. Called once at startup with the collector endpoint
+/// taken from config; if `endpoint` is `None`, OTel export stays disabled.
+pub fn init(endpoint: Option<&str>) -> anyhow::Result<()> {
+    let endpoint = match endpoint {
+        Some(e) => e,
+        None => return Ok(()),
+    };
+
+    let tracer_provider = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+        .install_batch(opentelemetry::runtime::AsyncStd)?;
+    global::set_tracer_provider(tracer_provider);
+
+    let meter: Meter = global::meter("curiefense");
+    let decisions_total = meter
+        .u64_counter("curiefense_decisions_total")
+        .with_description("Total decisions taken, labelled by secpolid/action_type/status_class")
+        .init();
+    let stage_timing = meter
+        .f64_histogram("curiefense_stage_duration_seconds")
+        .with_description("Per processing-stage timing, one observation per stage")
+        .init();
+
+    OTEL.set(OtelHandles {
+        decisions_total,
+        stage_timing,
+    })
+    .map_err(|_| anyhow::anyhow!("otel already initialized"))
+}
+
+fn status_class(rcode: Option<u32>) -> String {
+    match rcode {
+        Some(code) => format!("{}xx", code / 100),
+        None => "unknown".to_string(),
+    }
+}
+
+fn action_type_name(dec: &Decision) -> &'static str {
+    match dec.maction.as_ref().map(|a| a.atype) {
+        Some(ActionType::Block) => "block",
+        Some(ActionType::Monitor) => "monitor",
+        Some(ActionType::Skip) => "skip",
+        None => "pass",
+    }
+}
+
+/// Emits one span (named after `stats.processing_stage`) carrying the fields already present in
+/// `jsonlog_rinfo`, a span event per `BlockReason`, and records the counter/histogram
+/// instruments. No-op when `init` was never called.
+pub fn record_decision(dec: &Decision, rinfo: &RequestInfo, tags: &Tags, stats: &Stats, rcode: Option<u32>) {
+    let handles = match OTEL.get() {
+        Some(h) => h,
+        None => return,
+    };
+
+    let secpolid = rinfo.rinfo.secpolicy.policy.id.clone();
+    let atype = action_type_name(dec);
+    let sclass = status_class(rcode);
+
+    let tracer = global::tracer("curiefense");
+    let mut span = tracer.start(stats.processing_stage.to_string());
+    span.set_attribute(KeyValue::new("http.method", rinfo.rinfo.meta.method.clone()));
+    span.set_attribute(KeyValue::new("http.target", rinfo.rinfo.qinfo.qpath.clone()));
+    span.set_attribute(KeyValue::new(
+        "net.peer.ip",
+        rinfo.rinfo.geoip.ip.map(|ip| ip.to_string()).unwrap_or_default(),
+    ));
+    span.set_attribute(KeyValue::new("curiefense.secpolid", secpolid.clone()));
+    span.set_attribute(KeyValue::new("curiefense.action_type", atype));
+    if let Some(code) = rcode {
+        span.set_attribute(KeyValue::new("http.status_code", code as i64));
+    }
+
+    let greasons = BlockReason::regroup(&dec.reasons);
+    for (kind, reasons) in greasons.iter() {
+        for reason in reasons {
+            span.add_event(
+                format!("{:?}", kind),
+                vec![
+                    KeyValue::new("id", reason.id.clone()),
+                    KeyValue::new("name", reason.name.clone()),
+                    KeyValue::new("action", format!("{:?}", reason.action)),
+                ],
+            );
+        }
+    }
+    span.end();
+
+    handles.decisions_total.add(
+        1,
+        &[
+            KeyValue::new("secpolid", secpolid),
+            KeyValue::new("action_type", atype),
+            KeyValue::new("status_class", sclass),
+        ],
+    );
+
+    for (stage, duration) in stats.timing.iter() {
+        handles
+            .stage_timing
+            .record(duration.as_secs_f64(), &[KeyValue::new("stage", stage.clone())]);
+    }
+
+    let _ = tags; // tags are not exported; only the block reasons above are
+}
@@ -2,6 +2,7 @@ use crate::config::globalfilter::{
     GlobalFilterEntry, GlobalFilterEntryE, GlobalFilterSSection, GlobalFilterSection, PairEntry, SingleEntry,
 };
 use crate::config::raw::Relation;
+use crate::config::reload;
 use crate::interface::stats::{BStageMapped, BStageSecpol, StatsCollect};
 use crate::interface::{BlockReason, Location, SimpleActionT, SimpleDecision, Tags};
 use crate::requestfields::RequestField;
@@ -55,6 +56,12 @@ fn check_single(pr: &SingleEntry, s: &str, loc: Location) -> Option<HashSet<Loca
     }
 }
 
+/// Matches `pr` against both the raw value and its RFC 3986 normalized form, so a signature
+/// written against either one still fires regardless of how the request encoded its path.
+fn check_single_normalized(pr: &SingleEntry, s: &str, loc: Location) -> Option<HashSet<Location>> {
+    check_single(pr, s, loc.clone()).or_else(|| check_single(pr, &crate::config::waf::normalize_path(s), loc))
+}
+
 fn check_entry(rinfo: &RequestInfo, tags: &Tags, sub: &GlobalFilterEntry) -> MatchResult {
     fn bool(loc: Location, b: bool) -> Option<HashSet<Location>> {
         if b {
@@ -83,9 +90,9 @@ fn check_entry(rinfo: &RequestInfo, tags: &Tags, sub: &GlobalFilterEntry) -> Mat
                 _ => false,
             },
         ),
-        GlobalFilterEntryE::Path(pth) => check_single(pth, &rinfo.rinfo.qinfo.qpath, Location::Path),
-        GlobalFilterEntryE::Query(qry) => check_single(qry, &rinfo.rinfo.qinfo.query, Location::Path),
-        GlobalFilterEntryE::Uri(uri) => check_single(uri, &rinfo.rinfo.qinfo.uri, Location::Uri),
+        GlobalFilterEntryE::Path(pth) => check_single_normalized(pth, &rinfo.rinfo.qinfo.qpath, Location::Path),
+        GlobalFilterEntryE::Query(qry) => check_single_normalized(qry, &rinfo.rinfo.qinfo.query, Location::Path),
+        GlobalFilterEntryE::Uri(uri) => check_single_normalized(uri, &rinfo.rinfo.qinfo.uri, Location::Uri),
         GlobalFilterEntryE::Country(cty) => rinfo
             .rinfo
             .geoip
@@ -123,6 +130,17 @@ fn check_entry(rinfo: &RequestInfo, tags: &Tags, sub: &GlobalFilterEntry) -> Mat
             .and_then(|ccmp| check_single(cmp, ccmp.as_str(), Location::Ip)),
         GlobalFilterEntryE::Authority(at) => check_single(at, &rinfo.rinfo.host, Location::Request),
         GlobalFilterEntryE::Tag(tg) => tags.get(&tg.exact).cloned(),
+        GlobalFilterEntryE::AnonymousType(aty) => anonymous_category(&rinfo.rinfo.geoip)
+            .and_then(|category| check_single(aty, category, Location::Ip)),
+        GlobalFilterEntryE::Hosting => bool(Location::Ip, rinfo.rinfo.geoip.is_hosting.unwrap_or(false)),
+        GlobalFilterEntryE::Expr(compiled) => {
+            let r = crate::config::globalfilter::expr::eval(compiled, rinfo);
+            if r.matching {
+                Some(r.matched)
+            } else {
+                None
+            }
+        }
     };
     match r {
         Some(matched) => MatchResult {
@@ -136,16 +154,47 @@ fn check_entry(rinfo: &RequestInfo, tags: &Tags, sub: &GlobalFilterEntry) -> Mat
     }
 }
 
+/// Returns the most specific anonymizer sub-category the GeoIP anonymous-IP database reported for
+/// this request's address, in order of specificity: a Tor exit node is also a public proxy and an
+/// anonymous IP, so report the narrowest label that applies.
+fn anonymous_category(geoip: &crate::utils::GeoIp) -> Option<&'static str> {
+    if geoip.is_tor.unwrap_or(false) {
+        Some("tor")
+    } else if geoip.is_public_proxy.unwrap_or(false) {
+        Some("public_proxy")
+    } else if geoip.is_anonymous.unwrap_or(false) {
+        Some("anonymous")
+    } else {
+        None
+    }
+}
+
 fn check_subsection(rinfo: &RequestInfo, tags: &Tags, sub: &GlobalFilterSSection) -> MatchResult {
-    check_relation(rinfo, tags, sub.relation, &sub.entries, check_entry)
+    match (&sub.matcher, rinfo.rinfo.geoip.ip) {
+        (Some(matcher), Some(ip)) => match matcher.lookup(ip) {
+            Some(true) => MatchResult {
+                matched: std::iter::once(Location::Ip).collect(),
+                matching: true,
+            },
+            _ => MatchResult {
+                matched: HashSet::new(),
+                matching: false,
+            },
+        },
+        _ => check_relation(rinfo, tags, sub.relation, &sub.entries, check_entry),
+    }
 }
 
+/// Tags and (if a global filter blocks it) decides a request, always against the globalfilters
+/// in effect right now: it grabs one `reload::current()` snapshot per call, so a reload landing
+/// mid-request never produces a decision mixing old and new filters.
 pub fn tag_request(
     stats: StatsCollect<BStageSecpol>,
     is_human: bool,
-    globalfilters: &[GlobalFilterSection],
     rinfo: &RequestInfo,
 ) -> (Tags, SimpleDecision, StatsCollect<BStageMapped>) {
+    let snapshot = reload::current();
+    let globalfilters: &[GlobalFilterSection] = &snapshot.globalfilters;
     let mut tags = Tags::default();
     if is_human {
         tags.insert("human", Location::Request);
@@ -196,6 +245,12 @@ pub fn tag_request(
             tags.insert_qualified("geo-asn", &sasn, Location::Request);
         }
     }
+    if let Some(category) = anonymous_category(&rinfo.rinfo.geoip) {
+        tags.insert_qualified("geo-anon", category, Location::Request);
+    }
+    if rinfo.rinfo.geoip.is_hosting.unwrap_or(false) {
+        tags.insert("geo-hosting", Location::Request);
+    }
     let mut matched = 0;
     for psection in globalfilters {
         let mtch = check_relation(rinfo, &tags, psection.relation, &psection.sections, check_subsection);
@@ -356,15 +411,12 @@ mod tests {
     }
 
     fn optimize(ss: &GlobalFilterSSection) -> GlobalFilterSSection {
-        GlobalFilterSSection {
-            relation: ss.relation,
-            entries: optimize_ipranges(ss.relation, ss.entries.clone()),
-        }
+        GlobalFilterSSection::new(ss.relation, optimize_ipranges(ss.relation, ss.entries.clone()))
     }
 
     fn check_iprange(rel: Relation, input: &[&str], samples: &[(&str, bool)]) {
         let entries = mk_globalfilterentries(input);
-        let ssection = GlobalFilterSSection { entries, relation: rel };
+        let ssection = GlobalFilterSSection::new(rel, entries);
         let optimized = optimize(&ssection);
         let tags = Tags::default();
 
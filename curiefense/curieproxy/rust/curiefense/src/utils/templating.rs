@@ -0,0 +1,237 @@
+/// Parsing for the small `{{ ... }}` template language used by block/redirect action bodies and
+/// custom response headers. `render_template` (in `interface::mod`) is the evaluator; this module
+/// only owns the AST and the parser.
+use crate::config::matchers::RequestSelector;
+
+pub type RequestTemplate = Vec<TemplatePart<TVar>>;
+
+/// One node of a parsed template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplatePart<V> {
+    Raw(String),
+    Var(V),
+    /// A `{{#if}}`/`{{#unless}}`/`{{#each}}` block, matched into a balanced tree at parse time.
+    Section {
+        kind: SectionKind,
+        subject: V,
+        body: Vec<TemplatePart<V>>,
+        alt: Vec<TemplatePart<V>>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionKind {
+    If,
+    Unless,
+    Each,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TVar {
+    Selector(RequestSelector),
+    Tag(String),
+    /// A helper call, e.g. `{{ lower (selector ip) }}`: `arg` is resolved to a string first,
+    /// then `name` transforms it.
+    Helper { name: TemplateHelper, arg: Box<TVar> },
+    /// `{{ lookup headers (selector somevar) }}`: resolves `key` to a string, then indexes into
+    /// the named request map. Yields `nil` when the key is absent.
+    Lookup { map: RequestMap, key: Box<TVar> },
+}
+
+/// The request-side maps a `TVar::Lookup` can index into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestMap {
+    Headers,
+    Cookies,
+    Args,
+    PathSegments,
+}
+
+impl RequestMap {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "headers" => Some(RequestMap::Headers),
+            "cookies" => Some(RequestMap::Cookies),
+            "args" => Some(RequestMap::Args),
+            "path-segments" => Some(RequestMap::PathSegments),
+            _ => None,
+        }
+    }
+}
+
+/// Built-in string transforms usable inside a `TVar::Helper` call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateHelper {
+    Lower,
+    Upper,
+    Title,
+    Sha256,
+    Md5,
+    UrlEncode,
+    /// Quotes and escapes the value so it can be embedded verbatim in a JSON body.
+    JsonString,
+    /// Escapes `& < > " '` so the value can be embedded verbatim in an XML/HTML body.
+    XmlString,
+    /// Substitutes `fallback` when the wrapped selector resolves to `nil`.
+    Default(String),
+}
+
+impl TemplateHelper {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "lower" => Some(TemplateHelper::Lower),
+            "upper" => Some(TemplateHelper::Upper),
+            "title" => Some(TemplateHelper::Title),
+            "sha256" => Some(TemplateHelper::Sha256),
+            "md5" => Some(TemplateHelper::Md5),
+            "urlencode" => Some(TemplateHelper::UrlEncode),
+            "jsonstring" => Some(TemplateHelper::JsonString),
+            "xmlstring" => Some(TemplateHelper::XmlString),
+            _ => name.strip_prefix("default:").map(|lit| TemplateHelper::Default(unquote(lit))),
+        }
+    }
+}
+
+fn unquote(s: &str) -> String {
+    let s = s.trim();
+    s.strip_prefix('"').and_then(|s| s.strip_suffix('"')).unwrap_or(s).to_string()
+}
+
+/// Parses a template string into its AST. Unbalanced `{{#...}}` / `{{/...}}` markers are not
+/// reported as an error: a block that is left unclosed or closed by the wrong marker turns the
+/// whole input into one raw-text part, and a stray closing marker is kept, with everything after
+/// it, as raw text. Unknown tags fall back to a plain selector/tag variable, matching the previous
+/// (flat) behavior.
+pub fn parse_request_template(input: &str) -> RequestTemplate {
+    match parse_body(input) {
+        Ok((body, rest)) if rest.is_empty() => body,
+        // a stray/unbalanced closing marker: keep it and the rest of the template as raw text,
+        // rather than dropping it on the floor
+        Ok((body, rest)) => {
+            let mut body = body;
+            body.push(TemplatePart::Raw(rest.to_string()));
+            body
+        }
+        Err(_) => vec![TemplatePart::Raw(input.to_string())],
+    }
+}
+
+/// Parses template parts until either the input is exhausted or a `{{else}}`/`{{/...}}` marker
+/// is reached; returns the parts plus whatever text (including that marker) was left unconsumed.
+fn parse_body(mut input: &str) -> Result<(Vec<TemplatePart<TVar>>, &str), &'static str> {
+    let mut out = Vec::new();
+    loop {
+        match input.find("{{") {
+            None => {
+                if !input.is_empty() {
+                    out.push(TemplatePart::Raw(input.to_string()));
+                }
+                return Ok((out, ""));
+            }
+            Some(start) => {
+                if start > 0 {
+                    out.push(TemplatePart::Raw(input[..start].to_string()));
+                }
+                let after_open = &input[start + 2..];
+                let end = after_open.find("}}").ok_or("unterminated {{ tag")?;
+                let tag = after_open[..end].trim();
+                let rest = &after_open[end + 2..];
+
+                if tag == "else" || tag.starts_with('/') {
+                    return Ok((out, input));
+                }
+
+                if let Some(expr) = tag.strip_prefix("#if ") {
+                    let (body, after_body) = parse_body(rest)?;
+                    let (alt, after_alt) = take_else(after_body)?;
+                    let after_close = expect_close(after_alt, "if")?;
+                    out.push(TemplatePart::Section {
+                        kind: SectionKind::If,
+                        subject: parse_var(expr.trim()),
+                        body,
+                        alt,
+                    });
+                    input = after_close;
+                } else if let Some(expr) = tag.strip_prefix("#unless ") {
+                    let (body, after_body) = parse_body(rest)?;
+                    let (alt, after_alt) = take_else(after_body)?;
+                    let after_close = expect_close(after_alt, "unless")?;
+                    out.push(TemplatePart::Section {
+                        kind: SectionKind::Unless,
+                        subject: parse_var(expr.trim()),
+                        body,
+                        alt,
+                    });
+                    input = after_close;
+                } else if let Some(expr) = tag.strip_prefix("#each ") {
+                    let (body, after_body) = parse_body(rest)?;
+                    let after_close = expect_close(after_body, "each")?;
+                    out.push(TemplatePart::Section {
+                        kind: SectionKind::Each,
+                        subject: parse_var(expr.trim()),
+                        body,
+                        alt: Vec::new(),
+                    });
+                    input = after_close;
+                } else {
+                    out.push(TemplatePart::Var(parse_var(tag)));
+                    input = rest;
+                }
+            }
+        }
+    }
+}
+
+fn take_else(input: &str) -> Result<(Vec<TemplatePart<TVar>>, &str), &'static str> {
+    if let Some(after_open) = input.strip_prefix("{{") {
+        let end = after_open.find("}}").ok_or("unterminated {{ tag")?;
+        if after_open[..end].trim() == "else" {
+            return parse_body(&after_open[end + 2..]);
+        }
+    }
+    Ok((Vec::new(), input))
+}
+
+fn expect_close<'a>(input: &'a str, name: &str) -> Result<&'a str, &'static str> {
+    let after_open = input.strip_prefix("{{").ok_or("expected closing tag")?;
+    let end = after_open.find("}}").ok_or("unterminated {{ tag")?;
+    let closing = format!("/{}", name);
+    if after_open[..end].trim() != closing {
+        return Err("mismatched block closing tag");
+    }
+    Ok(&after_open[end + 2..])
+}
+
+/// Parses the (non-block) variable grammar: a bare tag name, `selector <name>`, or a helper call
+/// `<helper> (<arg>)` where `<arg>` is itself parsed with this same grammar.
+fn parse_var(expr: &str) -> TVar {
+    let expr = expr.trim();
+    if let Some(inner) = expr.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+        return parse_var(inner);
+    }
+    if let Some(name) = expr.strip_prefix("selector ") {
+        return match name.trim().parse::<RequestSelector>() {
+            Ok(sel) => TVar::Selector(sel),
+            Err(_) => TVar::Tag(name.trim().to_string()),
+        };
+    }
+    if let Some((head, rest)) = expr.split_once(' ') {
+        if head == "lookup" {
+            if let Some((mapname, keyexpr)) = rest.trim().split_once(' ') {
+                if let Some(map) = RequestMap::from_name(mapname) {
+                    return TVar::Lookup {
+                        map,
+                        key: Box::new(parse_var(keyexpr.trim())),
+                    };
+                }
+            }
+        }
+        if let Some(helper) = TemplateHelper::from_name(head) {
+            return TVar::Helper {
+                name: helper,
+                arg: Box::new(parse_var(rest.trim())),
+            };
+        }
+    }
+    TVar::Tag(expr.to_string())
+}